@@ -0,0 +1,148 @@
+use candid::CandidType;
+use ic_cdk::{query, update};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    add_to_owner_index, next_seq, remove_from_owner_index, HashKey, OwnerHashes, PrincipalWrapper,
+    RegistrationStatus, ADMIN, OWNER_INDEX, REGISTRY,
+};
+
+fn require_admin() -> Result<(), String> {
+    let caller = ic_cdk::api::caller();
+    let admin = ADMIN.with(|admin| admin.borrow().get().0);
+    if caller != admin {
+        return Err("caller is not the registry admin".to_string());
+    }
+    Ok(())
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Default)]
+pub struct RegistryStats {
+    pub total_registrations: u64,
+    pub active: u64,
+    pub transferred: u64,
+    pub expired: u64,
+    pub total_transfer_events: u64,
+    pub oldest_timestamp: Option<u64>,
+    pub newest_timestamp: Option<u64>,
+}
+
+#[query]
+fn stats() -> Result<RegistryStats, String> {
+    require_admin()?;
+
+    let stats = REGISTRY.with(|registry| {
+        let mut stats = RegistryStats::default();
+        for (_, reg) in registry.borrow().iter() {
+            stats.total_registrations += 1;
+            match reg.status {
+                RegistrationStatus::Active => stats.active += 1,
+                RegistrationStatus::Transferred => stats.transferred += 1,
+                RegistrationStatus::Expired => stats.expired += 1,
+            }
+            stats.total_transfer_events += reg.transfer_history.len() as u64;
+            stats.oldest_timestamp = Some(
+                stats.oldest_timestamp.map_or(reg.timestamp, |t| t.min(reg.timestamp)),
+            );
+            stats.newest_timestamp = Some(
+                stats.newest_timestamp.map_or(reg.timestamp, |t| t.max(reg.timestamp)),
+            );
+        }
+        stats
+    });
+
+    Ok(stats)
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone, Default)]
+pub struct RepairReport {
+    pub stale_owner_after_transfer_fixed: u64,
+    pub orphaned_owner_index_entries_removed: u64,
+}
+
+#[update]
+fn repair() -> Result<RepairReport, String> {
+    require_admin()?;
+
+    let mut report = RepairReport::default();
+
+    // A registration marked Transferred whose owner still equals the `from`
+    // side of its *first* transfer record was left in an inconsistent state
+    // by an interrupted transfer: ownership was never advanced at all, no
+    // matter how many transfers were subsequently recorded. Recompute the
+    // owner from the most recent transfer record. A round trip back to the
+    // original owner (the last record's `to` is already the current owner)
+    // is left alone and not counted.
+    let stale_keys: Vec<HashKey> = REGISTRY.with(|registry| {
+        registry
+            .borrow()
+            .iter()
+            .filter(|(_, reg)| {
+                reg.status == RegistrationStatus::Transferred
+                    && reg.transfer_history.first().map_or(false, |first| first.from == reg.owner)
+            })
+            .map(|(key, _)| key)
+            .collect()
+    });
+
+    for key in stale_keys {
+        REGISTRY.with(|registry| {
+            let mut registry = registry.borrow_mut();
+            if let Some(mut reg) = registry.get(&key) {
+                if let Some(new_owner) = reg
+                    .transfer_history
+                    .last()
+                    .map(|last| last.to.clone())
+                    .filter(|to| *to != reg.owner)
+                {
+                    let previous_owner = reg.owner.clone();
+                    reg.owner = new_owner.clone();
+                    reg.last_modified_seq = next_seq();
+                    registry.insert(key.clone(), reg);
+                    remove_from_owner_index(&previous_owner, &key.0);
+                    add_to_owner_index(&new_owner, &key.0);
+                    report.stale_owner_after_transfer_fixed += 1;
+                }
+            }
+        });
+    }
+
+    // Drop owner-index entries that no longer point at a registration
+    // actually owned by that principal.
+    let owners: Vec<PrincipalWrapper> =
+        OWNER_INDEX.with(|index| index.borrow().iter().map(|(owner, _)| owner).collect());
+
+    for owner in owners {
+        let original = OWNER_INDEX
+            .with(|index| index.borrow().get(&owner))
+            .unwrap_or_default()
+            .0;
+
+        let valid: Vec<String> = original
+            .iter()
+            .filter(|hash| {
+                REGISTRY.with(|registry| {
+                    registry
+                        .borrow()
+                        .get(&HashKey((*hash).clone()))
+                        .map_or(false, |reg| reg.owner == owner)
+                })
+            })
+            .cloned()
+            .collect();
+
+        if valid.len() != original.len() {
+            report.orphaned_owner_index_entries_removed += (original.len() - valid.len()) as u64;
+            OWNER_INDEX.with(|index| {
+                let mut index = index.borrow_mut();
+                if valid.is_empty() {
+                    index.remove(&owner);
+                } else {
+                    index.insert(owner.clone(), OwnerHashes(valid));
+                }
+            });
+        }
+    }
+
+    Ok(report)
+}
@@ -1,19 +1,52 @@
 use candid::{CandidType, Principal};
+use chrono::{DateTime, NaiveDateTime};
 use ic_cdk::{update, query};
+use ic_cdk_timers::set_timer_interval;
 use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
 use std::collections::HashMap;
+use std::time::Duration;
 use ic_stable_structures::{
     memory_manager::{MemoryId, MemoryManager, VirtualMemory},
-    DefaultMemoryImpl, 
-    StableBTreeMap, 
+    Cell as StableCell,
+    DefaultMemoryImpl,
+    StableBTreeMap,
     BoundedStorable,
     Storable,
 };
 use std::cell::RefCell;
 use std::borrow::Cow;
 
+mod admin;
+
+#[derive(CandidType, Deserialize, Serialize, Clone)]
+pub(crate) struct IPRegistration {
+    pub(crate) owner: PrincipalWrapper,
+    pub(crate) title: String,
+    pub(crate) description: String,
+    pub(crate) timestamp: u64,
+    pub(crate) file_hash: String,
+    pub(crate) license_type: String,
+    pub(crate) metadata: HashMap<String, String>,
+    pub(crate) transfer_history: Vec<TransferRecord>,
+    pub(crate) status: RegistrationStatus,
+    // Nanoseconds since the Unix epoch (ic_cdk::api::time() units); u64::MAX means "never expires".
+    pub(crate) expiry: u64,
+    // Bumped from the global SEQ_COUNTER on every write; lets clients poll
+    // `get_changes_since` instead of re-running a full search.
+    pub(crate) last_modified_seq: u64,
+    // Set by a successful `seal_file`, once the stored chunks have been
+    // verified to hash to `file_hash`. `upload_chunk` refuses to touch a
+    // sealed file's chunks, so a sealed file's content can't be silently
+    // overwritten out from under its verified hash.
+    pub(crate) sealed: bool,
+}
+
+// Pre-schema-v5 shape of IPRegistration, kept only so `migrate_v3_to_current`
+// and `migrate_v4_to_current` can decode records written before the `sealed`
+// field existed.
 #[derive(CandidType, Deserialize, Serialize, Clone)]
-struct IPRegistration {
+struct IPRegistrationV3 {
     owner: PrincipalWrapper,
     title: String,
     description: String,
@@ -23,17 +56,95 @@ struct IPRegistration {
     metadata: HashMap<String, String>,
     transfer_history: Vec<TransferRecord>,
     status: RegistrationStatus,
+    expiry: u64,
+    last_modified_seq: u64,
+}
+
+impl Storable for IPRegistrationV3 {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
 }
 
+impl BoundedStorable for IPRegistrationV3 {
+    const MAX_SIZE: u32 = 4096;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Pre-schema-v2 shape of IPRegistration, kept only so `migrate_v0_to_current` and
+// `migrate_v1_to_current` can decode records written before the `expiry` field existed.
 #[derive(CandidType, Deserialize, Serialize, Clone)]
-struct TransferRecord {
-    from: PrincipalWrapper,
-    to: PrincipalWrapper,
+struct IPRegistrationV1 {
+    owner: PrincipalWrapper,
+    title: String,
+    description: String,
     timestamp: u64,
+    file_hash: String,
+    license_type: String,
+    metadata: HashMap<String, String>,
+    transfer_history: Vec<TransferRecord>,
+    status: RegistrationStatus,
+}
+
+impl Storable for IPRegistrationV1 {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+impl BoundedStorable for IPRegistrationV1 {
+    const MAX_SIZE: u32 = 1024;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Pre-schema-v3 shape of IPRegistration, kept only so `migrate_v2_to_current` can
+// decode records written before the `last_modified_seq` field existed.
+#[derive(CandidType, Deserialize, Serialize, Clone)]
+struct IPRegistrationV2 {
+    owner: PrincipalWrapper,
+    title: String,
+    description: String,
+    timestamp: u64,
+    file_hash: String,
+    license_type: String,
+    metadata: HashMap<String, String>,
+    transfer_history: Vec<TransferRecord>,
+    status: RegistrationStatus,
+    expiry: u64,
+}
+
+impl Storable for IPRegistrationV2 {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+impl BoundedStorable for IPRegistrationV2 {
+    const MAX_SIZE: u32 = 4096;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone)]
+pub(crate) struct TransferRecord {
+    pub(crate) from: PrincipalWrapper,
+    pub(crate) to: PrincipalWrapper,
+    pub(crate) timestamp: u64,
 }
 
 #[derive(CandidType, Deserialize, Serialize, Clone, PartialEq)]
-enum RegistrationStatus {
+pub(crate) enum RegistrationStatus {
     Active,
     Transferred,
     Expired,
@@ -41,7 +152,7 @@ enum RegistrationStatus {
 
 // Newtype wrapper for Principal
 #[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
-struct PrincipalWrapper(Principal);
+pub(crate) struct PrincipalWrapper(pub(crate) Principal);
 
 // Implement Storable for PrincipalWrapper
 impl Storable for PrincipalWrapper {
@@ -60,6 +171,109 @@ impl BoundedStorable for PrincipalWrapper {
     const IS_FIXED_SIZE: bool = true;
 }
 
+// Newtype wrapper so the registry can be keyed by file_hash instead of owner
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+pub(crate) struct HashKey(pub(crate) String);
+
+// Implement Storable for HashKey
+impl Storable for HashKey {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(self.0.as_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        HashKey(String::from_utf8(bytes.into_owned()).expect("invalid utf8 in HashKey"))
+    }
+}
+
+// Implement BoundedStorable for HashKey
+impl BoundedStorable for HashKey {
+    const MAX_SIZE: u32 = 256;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// A principal's list of file hashes, stored in the secondary owner index
+#[derive(CandidType, Deserialize, Serialize, Clone, Default)]
+pub(crate) struct OwnerHashes(pub(crate) Vec<String>);
+
+impl Storable for OwnerHashes {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(candid::encode_one(self).unwrap())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        candid::decode_one(&bytes).unwrap()
+    }
+}
+
+impl BoundedStorable for OwnerHashes {
+    const MAX_SIZE: u32 = 8192;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// How large a single on-chain content chunk may be; `upload_chunk` rejects
+// anything bigger and requires chunk-aligned offsets.
+const CHUNK_SIZE: u64 = 64 * 1024;
+
+// Largest range `get_file_range` will assemble in one call, so a caller
+// can't force an arbitrarily large allocation/response via `len`.
+const MAX_RANGE_LEN: u64 = 16 * CHUNK_SIZE;
+
+// Composite key for on-chain file content: a file_hash plus the index of one
+// of its fixed-size chunks. Deriving Ord on the tuple orders keys grouped by
+// file_hash, then by chunk index.
+#[derive(CandidType, Deserialize, Serialize, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+struct ChunkKey(HashKey, u32);
+
+impl Storable for ChunkKey {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        let hash_bytes = self.0 .0.as_bytes();
+        let mut buf = Vec::with_capacity(4 + hash_bytes.len() + 4);
+        buf.extend_from_slice(&(hash_bytes.len() as u32).to_be_bytes());
+        buf.extend_from_slice(hash_bytes);
+        buf.extend_from_slice(&self.1.to_be_bytes());
+        Cow::Owned(buf)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let mut len_buf = [0u8; 4];
+        len_buf.copy_from_slice(&bytes[0..4]);
+        let hash_len = u32::from_be_bytes(len_buf) as usize;
+        let hash = String::from_utf8(bytes[4..4 + hash_len].to_vec())
+            .expect("invalid utf8 in ChunkKey");
+        let mut index_buf = [0u8; 4];
+        index_buf.copy_from_slice(&bytes[4 + hash_len..8 + hash_len]);
+        ChunkKey(HashKey(hash), u32::from_be_bytes(index_buf))
+    }
+}
+
+impl BoundedStorable for ChunkKey {
+    const MAX_SIZE: u32 = 4 + HashKey::MAX_SIZE + 4;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+// Raw bytes of a single chunk of on-chain file content.
+#[derive(CandidType, Deserialize, Serialize, Clone, Default)]
+struct ChunkData(Vec<u8>);
+
+impl Storable for ChunkData {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Borrowed(&self.0)
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        ChunkData(bytes.into_owned())
+    }
+}
+
+impl BoundedStorable for ChunkData {
+    const MAX_SIZE: u32 = CHUNK_SIZE as u32;
+    const IS_FIXED_SIZE: bool = false;
+}
+
+fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|b| format!("{b:02x}")).collect()
+}
 
 // Implement Storable for IPRegistration
 impl Storable for IPRegistration {
@@ -74,43 +288,347 @@ impl Storable for IPRegistration {
 
 // Implement BoundedStorable for IPRegistration
 impl BoundedStorable for IPRegistration {
-    const MAX_SIZE: u32 = 1024;
+    const MAX_SIZE: u32 = 4096;
     const IS_FIXED_SIZE: bool = false;
 }
 
+// Schema version persisted across upgrades, so post_upgrade knows which
+// migrations (if any) still need to run.
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Default)]
+struct SchemaVersion(u32);
+
+impl Storable for SchemaVersion {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(self.0.to_le_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let mut buf = [0u8; 4];
+        buf.copy_from_slice(&bytes);
+        SchemaVersion(u32::from_le_bytes(buf))
+    }
+}
+
+impl BoundedStorable for SchemaVersion {
+    const MAX_SIZE: u32 = 4;
+    const IS_FIXED_SIZE: bool = true;
+}
+
+// Monotonically increasing change-feed sequence number, bumped on every
+// write so clients can cheaply poll `get_changes_since`.
+#[derive(CandidType, Deserialize, Serialize, Clone, Copy, Default)]
+struct SeqCounter(u64);
+
+impl Storable for SeqCounter {
+    fn to_bytes(&self) -> Cow<[u8]> {
+        Cow::Owned(self.0.to_le_bytes().to_vec())
+    }
+
+    fn from_bytes(bytes: Cow<[u8]>) -> Self {
+        let mut buf = [0u8; 8];
+        buf.copy_from_slice(&bytes);
+        SeqCounter(u64::from_le_bytes(buf))
+    }
+}
+
+impl BoundedStorable for SeqCounter {
+    const MAX_SIZE: u32 = 8;
+    const IS_FIXED_SIZE: bool = true;
+}
+
+// Bumped whenever IPRegistration (or another persisted type or its storage
+// location) changes in a way that needs a migration step in
+// `run_migrations` — a field addition, or REGISTRY's move off MemoryId(0)
+// in schema 4.
+const CURRENT_SCHEMA_VERSION: u32 = 5;
+
 thread_local! {
-    static REGISTRY: RefCell<StableBTreeMap<PrincipalWrapper, IPRegistration, VirtualMemory<DefaultMemoryImpl>>> = 
+    static MEMORY_MANAGER: RefCell<MemoryManager<DefaultMemoryImpl>> =
+        RefCell::new(MemoryManager::init(DefaultMemoryImpl::default()));
+
+    // Primary store, keyed by file_hash so a principal can own more than one IP.
+    // Lives on MemoryId(6) rather than the original MemoryId(0): `init` reads a
+    // region's `max_value_size` from bytes already persisted there, so once
+    // IPRegistration's shape (and `BoundedStorable::MAX_SIZE`) changed across
+    // schema versions, re-using MemoryId(0) for the migrated, larger records
+    // risked capping them at the old bound. MemoryId(0) is kept around purely
+    // as the read source the migrations below decode legacy data from.
+    pub(crate) static REGISTRY: RefCell<StableBTreeMap<HashKey, IPRegistration, VirtualMemory<DefaultMemoryImpl>>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(6)))
+        ));
+
+    // Secondary index: owner -> all file hashes they currently own.
+    pub(crate) static OWNER_INDEX: RefCell<StableBTreeMap<PrincipalWrapper, OwnerHashes, VirtualMemory<DefaultMemoryImpl>>> =
+        RefCell::new(StableBTreeMap::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(1)))
+        ));
+
+    // Defaults to `SchemaVersion(0)` because an empty cell is ambiguous: it's
+    // what a brand-new install sees before `init` runs, but it's also what a
+    // canister that predates this cell's existence sees on its first upgrade
+    // into this series. Treating "empty" as "legacy" lets `post_upgrade` tell
+    // the two apart and run `migrate_v0_to_current` for the latter; `init`
+    // overwrites this with `CURRENT_SCHEMA_VERSION` immediately on a real
+    // fresh install.
+    static SCHEMA_VERSION: RefCell<StableCell<SchemaVersion, VirtualMemory<DefaultMemoryImpl>>> =
+        RefCell::new(StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(2))),
+            SchemaVersion(0),
+        ).expect("failed to init schema version cell"));
+
+    pub(crate) static ADMIN: RefCell<StableCell<PrincipalWrapper, VirtualMemory<DefaultMemoryImpl>>> =
+        RefCell::new(StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(3))),
+            PrincipalWrapper(Principal::anonymous()),
+        ).expect("failed to init admin cell"));
+
+    static SEQ_COUNTER: RefCell<StableCell<SeqCounter, VirtualMemory<DefaultMemoryImpl>>> =
+        RefCell::new(StableCell::init(
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(4))),
+            SeqCounter(0),
+        ).expect("failed to init sequence counter cell"));
+
+    // On-chain file content, chunked by file_hash + chunk index.
+    static FILE_CHUNKS: RefCell<StableBTreeMap<ChunkKey, ChunkData, VirtualMemory<DefaultMemoryImpl>>> =
         RefCell::new(StableBTreeMap::init(
-            MemoryManager::init(DefaultMemoryImpl::default())
-                .get(MemoryId::new(0))
+            MEMORY_MANAGER.with(|m| m.borrow().get(MemoryId::new(5)))
         ));
 }
 
-#[update]
-fn transfer_ownership(file_hash: String, new_owner: Principal) -> Result<(), String> {
-    let caller = ic_cdk::api::caller();
+pub(crate) fn next_seq() -> u64 {
+    SEQ_COUNTER.with(|counter| {
+        let mut counter = counter.borrow_mut();
+        let next = counter.get().0 + 1;
+        counter.set(SeqCounter(next)).expect("failed to set sequence counter");
+        next
+    })
+}
+
+// Dispatches straight to the migration that decodes `from_version`'s legacy
+// shape and writes the current `IPRegistration` shape into `REGISTRY`
+// (MemoryId(6)); each step targets `CURRENT_SCHEMA_VERSION` directly rather
+// than chaining through intermediate shapes. Schemas 0-3 are read off
+// MemoryId(0); schema 4 records already sit on MemoryId(6) and are rewritten
+// in place.
+fn run_migrations(from_version: u32) {
+    match from_version {
+        0 => migrate_v0_to_current(),
+        1 => migrate_v1_to_current(),
+        2 => migrate_v2_to_current(),
+        3 => migrate_v3_to_current(),
+        4 => migrate_v4_to_current(),
+        _ => {}
+    }
+}
+
+// Pure field-mapping logic for `migrate_v0_to_current`/`migrate_v1_to_current`,
+// kept separate from the stable-memory I/O around it so it can be unit
+// tested without a canister environment.
+fn upgrade_v1(old: IPRegistrationV1) -> IPRegistration {
+    IPRegistration {
+        owner: old.owner,
+        title: old.title,
+        description: old.description,
+        timestamp: old.timestamp,
+        file_hash: old.file_hash,
+        license_type: old.license_type,
+        metadata: old.metadata,
+        transfer_history: old.transfer_history,
+        status: old.status,
+        expiry: u64::MAX,
+        last_modified_seq: 0,
+        sealed: false,
+    }
+}
+
+// Pure field-mapping logic for `migrate_v2_to_current`.
+fn upgrade_v2(old: IPRegistrationV2) -> IPRegistration {
+    IPRegistration {
+        owner: old.owner,
+        title: old.title,
+        description: old.description,
+        timestamp: old.timestamp,
+        file_hash: old.file_hash,
+        license_type: old.license_type,
+        metadata: old.metadata,
+        transfer_history: old.transfer_history,
+        status: old.status,
+        expiry: old.expiry,
+        last_modified_seq: 0,
+        sealed: false,
+    }
+}
+
+// Pure field-mapping logic for `migrate_v3_to_current`/`migrate_v4_to_current`.
+fn upgrade_v3(old: IPRegistrationV3) -> IPRegistration {
+    IPRegistration {
+        owner: old.owner,
+        title: old.title,
+        description: old.description,
+        timestamp: old.timestamp,
+        file_hash: old.file_hash,
+        license_type: old.license_type,
+        metadata: old.metadata,
+        transfer_history: old.transfer_history,
+        status: old.status,
+        expiry: old.expiry,
+        last_modified_seq: old.last_modified_seq,
+        sealed: false,
+    }
+}
+
+// Re-opens MemoryId(0) through the pre-versioning, owner-keyed shape used
+// before `IPRegistration` existed in this series at all: one record per
+// owner, keyed by `PrincipalWrapper` instead of file_hash, with the same
+// fields as `IPRegistrationV1`. Rekeys by file_hash and rebuilds the owner
+// index, neither of which existed yet at this point.
+fn migrate_v0_to_current() {
+    let old_entries: Vec<(PrincipalWrapper, IPRegistrationV1)> = MEMORY_MANAGER.with(|m| {
+        let memory = m.borrow().get(MemoryId::new(0));
+        let old_registry: StableBTreeMap<PrincipalWrapper, IPRegistrationV1, _> =
+            StableBTreeMap::init(memory);
+        old_registry.iter().collect()
+    });
+
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        for (owner, old) in old_entries {
+            let key = HashKey(old.file_hash.clone());
+            add_to_owner_index(&owner, &key.0);
+            registry.insert(key, upgrade_v1(old));
+        }
+    });
+}
+
+// Re-opens MemoryId(0) through the pre-expiry-field `IPRegistrationV1` shape
+// to decode existing records, then rewrites them through `REGISTRY` with
+// `expiry` defaulted to "never expires".
+fn migrate_v1_to_current() {
+    let old_entries: Vec<(HashKey, IPRegistrationV1)> = MEMORY_MANAGER.with(|m| {
+        let memory = m.borrow().get(MemoryId::new(0));
+        let old_registry: StableBTreeMap<HashKey, IPRegistrationV1, _> = StableBTreeMap::init(memory);
+        old_registry.iter().collect()
+    });
+
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        for (key, old) in old_entries {
+            registry.insert(key, upgrade_v1(old));
+        }
+    });
+}
+
+// Re-opens MemoryId(0) through the pre-change-feed `IPRegistrationV2` shape
+// to decode existing records, then rewrites them through `REGISTRY` with
+// `last_modified_seq` defaulted to 0 (predates the change feed).
+fn migrate_v2_to_current() {
+    let old_entries: Vec<(HashKey, IPRegistrationV2)> = MEMORY_MANAGER.with(|m| {
+        let memory = m.borrow().get(MemoryId::new(0));
+        let old_registry: StableBTreeMap<HashKey, IPRegistrationV2, _> = StableBTreeMap::init(memory);
+        old_registry.iter().collect()
+    });
+
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        for (key, old) in old_entries {
+            registry.insert(key, upgrade_v2(old));
+        }
+    });
+}
+
+// Handles a canister already on schema 3 whose REGISTRY was still
+// physically on MemoryId(0) (every commit before 554568f moved the live
+// thread_local there but never relocated already-persisted data). Schema 3
+// predates `sealed`, so this decodes through `IPRegistrationV3` (the same
+// shape `migrate_v4_to_current` reads) and defaults it to false. The owner
+// index already covers these records, but is re-added defensively,
+// mirroring `migrate_v0_to_current`.
+fn migrate_v3_to_current() {
+    let old_entries: Vec<(HashKey, IPRegistrationV3)> = MEMORY_MANAGER.with(|m| {
+        let memory = m.borrow().get(MemoryId::new(0));
+        let old_registry: StableBTreeMap<HashKey, IPRegistrationV3, _> = StableBTreeMap::init(memory);
+        old_registry.iter().collect()
+    });
+
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        for (key, old) in old_entries {
+            add_to_owner_index(&old.owner, &key.0);
+            registry.insert(key, upgrade_v3(old));
+        }
+    });
+}
+
+// Schema 4 records already live on MemoryId(6) (the current REGISTRY
+// location) but predate `sealed`; decodes them through `IPRegistrationV3`
+// and rewrites them in place with `sealed` defaulted to false.
+fn migrate_v4_to_current() {
+    let old_entries: Vec<(HashKey, IPRegistrationV3)> = MEMORY_MANAGER.with(|m| {
+        let memory = m.borrow().get(MemoryId::new(6));
+        let old_registry: StableBTreeMap<HashKey, IPRegistrationV3, _> = StableBTreeMap::init(memory);
+        old_registry.iter().collect()
+    });
+
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        for (key, old) in old_entries {
+            registry.insert(key, upgrade_v3(old));
+        }
+    });
+}
+
+pub(crate) fn add_to_owner_index(owner: &PrincipalWrapper, file_hash: &str) {
+    OWNER_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        let mut hashes = index.get(owner).unwrap_or_default();
+        if !hashes.0.iter().any(|h| h == file_hash) {
+            hashes.0.push(file_hash.to_string());
+        }
+        index.insert(owner.clone(), hashes);
+    });
+}
+
+pub(crate) fn remove_from_owner_index(owner: &PrincipalWrapper, file_hash: &str) {
+    OWNER_INDEX.with(|index| {
+        let mut index = index.borrow_mut();
+        if let Some(mut hashes) = index.get(owner) {
+            hashes.0.retain(|h| h != file_hash);
+            if hashes.0.is_empty() {
+                index.remove(owner);
+            } else {
+                index.insert(owner.clone(), hashes);
+            }
+        }
+    });
+}
+
+fn transfer_ownership_internal(caller: Principal, file_hash: String, new_owner: Principal) -> Result<(), String> {
     let timestamp = ic_cdk::api::time();
 
     REGISTRY.with(|registry| {
         let mut registry = registry.borrow_mut();
-        
-        if let Some(mut registration) = registry.get(&PrincipalWrapper(caller)) {
-            if registration.file_hash != file_hash {
+        let key = HashKey(file_hash.clone());
+
+        if let Some(mut registration) = registry.get(&key) {
+            if registration.owner != PrincipalWrapper(caller) {
                 return Err("You don't own this IP".to_string());
             }
-            
+
             let transfer_record = TransferRecord {
                 from: PrincipalWrapper(caller),
                 to: PrincipalWrapper(new_owner),
                 timestamp,
             };
-            
+
             registration.transfer_history.push(transfer_record);
             registration.owner = PrincipalWrapper(new_owner);
             registration.status = RegistrationStatus::Transferred;
-            
-            registry.remove(&PrincipalWrapper(caller));
-            registry.insert(PrincipalWrapper(new_owner), registration);
+            registration.last_modified_seq = next_seq();
+
+            registry.insert(key, registration);
+            remove_from_owner_index(&PrincipalWrapper(caller), &file_hash);
+            add_to_owner_index(&PrincipalWrapper(new_owner), &file_hash);
             Ok(())
         } else {
             Err("IP registration not found".to_string())
@@ -118,6 +636,20 @@ fn transfer_ownership(file_hash: String, new_owner: Principal) -> Result<(), Str
     })
 }
 
+#[update]
+fn transfer_ownership(file_hash: String, new_owner: Principal) -> Result<(), String> {
+    transfer_ownership_internal(ic_cdk::api::caller(), file_hash, new_owner)
+}
+
+#[update]
+fn transfer_ownership_batch(transfers: Vec<(String, Principal)>) -> Vec<Result<(), String>> {
+    let caller = ic_cdk::api::caller();
+    transfers
+        .into_iter()
+        .map(|(file_hash, new_owner)| transfer_ownership_internal(caller, file_hash, new_owner))
+        .collect()
+}
+
 #[query]
 fn search_registrations(query: String) -> Vec<IPRegistration> {
     let query = query.to_lowercase();
@@ -140,9 +672,8 @@ fn get_transfer_history(file_hash: String) -> Result<Vec<TransferRecord>, String
     REGISTRY.with(|registry| {
         registry
             .borrow()
-            .iter()
-            .find(|(_, reg)| reg.file_hash == file_hash)
-            .map(|(_, reg)| reg.transfer_history)
+            .get(&HashKey(file_hash))
+            .map(|reg| reg.transfer_history)
             .ok_or_else(|| "IP registration not found".to_string())
     })
 }
@@ -150,17 +681,19 @@ fn get_transfer_history(file_hash: String) -> Result<Vec<TransferRecord>, String
 #[update]
 fn update_registration_status(file_hash: String, status: RegistrationStatus) -> Result<(), String> {
     let caller = ic_cdk::api::caller();
-    
+
     REGISTRY.with(|registry| {
         let mut registry = registry.borrow_mut();
-        
-        if let Some(mut registration) = registry.get(&PrincipalWrapper(caller)) {
-            if registration.file_hash != file_hash {
+        let key = HashKey(file_hash);
+
+        if let Some(mut registration) = registry.get(&key) {
+            if registration.owner != PrincipalWrapper(caller) {
                 return Err("You don't own this IP".to_string());
             }
-            
+
             registration.status = status;
-            registry.insert(PrincipalWrapper(caller), registration);
+            registration.last_modified_seq = next_seq();
+            registry.insert(key, registration);
             Ok(())
         } else {
             Err("IP registration not found".to_string())
@@ -168,39 +701,174 @@ fn update_registration_status(file_hash: String, status: RegistrationStatus) ->
     })
 }
 
-#[update]
-fn register_ip(
+// Describes how an `expiry` string supplied by a caller should be parsed
+// into canister time (nanoseconds since the Unix epoch).
+#[derive(CandidType, Deserialize, Serialize, Clone)]
+enum Conversion {
+    // Either "unix:<secs>" or an RFC3339 timestamp, e.g. "2027-01-01T00:00:00Z".
+    Timestamp,
+    // A chrono strftime format with no timezone; the input is treated as UTC.
+    TimestampFmt(String),
+    // A chrono strftime format whose pattern includes an explicit UTC offset.
+    TimestampTZFmt(String),
+}
+
+fn parse_expiry(value: &str, conversion: &Conversion) -> Result<u64, String> {
+    let dt = match conversion {
+        Conversion::Timestamp => {
+            if let Some(secs) = value.strip_prefix("unix:") {
+                let secs: i64 = secs
+                    .parse()
+                    .map_err(|_| format!("invalid unix timestamp: {value}"))?;
+                DateTime::from_timestamp(secs, 0)
+                    .ok_or_else(|| format!("unix timestamp out of range: {value}"))?
+                    .fixed_offset()
+            } else {
+                DateTime::parse_from_rfc3339(value)
+                    .map_err(|e| format!("invalid RFC3339 expiry '{value}': {e}"))?
+            }
+        }
+        Conversion::TimestampFmt(fmt) => {
+            let naive = NaiveDateTime::parse_from_str(value, fmt)
+                .map_err(|e| format!("invalid expiry '{value}' for format '{fmt}': {e}"))?;
+            naive.and_utc().fixed_offset()
+        }
+        Conversion::TimestampTZFmt(fmt) => DateTime::parse_from_str(value, fmt)
+            .map_err(|e| format!("invalid expiry '{value}' for format '{fmt}': {e}"))?,
+    };
+
+    let nanos = dt
+        .timestamp_nanos_opt()
+        .ok_or_else(|| format!("expiry out of range: {value}"))?;
+    if nanos < 0 {
+        return Err(format!("expiry must not be before the Unix epoch: {value}"));
+    }
+    Ok(nanos as u64)
+}
+
+#[derive(CandidType, Deserialize, Serialize, Clone)]
+struct RegisterArgs {
     title: String,
     description: String,
     file_hash: String,
     license_type: String,
     metadata: HashMap<String, String>,
-) -> Result<(), String> {
-    let caller = ic_cdk::api::caller();
+    expiry: String,
+    expiry_format: Conversion,
+}
+
+fn register_ip_internal(caller: Principal, args: RegisterArgs) -> Result<(), String> {
     let timestamp = ic_cdk::api::time();
+    let key = HashKey(args.file_hash.clone());
+    let expiry = parse_expiry(&args.expiry, &args.expiry_format)?;
+
+    let already_registered = REGISTRY.with(|registry| registry.borrow().contains_key(&key));
+    if already_registered {
+        return Err("An IP with this file hash is already registered".to_string());
+    }
 
     let registration = IPRegistration {
         owner: PrincipalWrapper(caller),
-        title,
-        description,
+        title: args.title,
+        description: args.description,
         timestamp,
-        file_hash,
-        license_type,
-        metadata,
+        file_hash: args.file_hash.clone(),
+        license_type: args.license_type,
+        metadata: args.metadata,
         transfer_history: Vec::new(), // Initialize with empty vector
-        status: RegistrationStatus::Active // Initialize with Active status
+        status: RegistrationStatus::Active, // Initialize with Active status
+        expiry,
+        last_modified_seq: next_seq(),
+        sealed: false,
     };
 
+    let encoded_len = candid::encode_one(&registration)
+        .map(|bytes| bytes.len())
+        .unwrap_or(usize::MAX);
+    if encoded_len > IPRegistration::MAX_SIZE as usize {
+        return Err(format!(
+            "registration is too large to store ({encoded_len} bytes, max {})",
+            IPRegistration::MAX_SIZE
+        ));
+    }
+
     REGISTRY.with(|registry| {
-        registry.borrow_mut().insert(PrincipalWrapper(caller), registration);
+        registry.borrow_mut().insert(key, registration);
     });
+    add_to_owner_index(&PrincipalWrapper(caller), &args.file_hash);
 
     Ok(())
 }
 
+#[update]
+fn register_ip(
+    title: String,
+    description: String,
+    file_hash: String,
+    license_type: String,
+    metadata: HashMap<String, String>,
+    expiry: String,
+    expiry_format: Conversion,
+) -> Result<(), String> {
+    register_ip_internal(
+        ic_cdk::api::caller(),
+        RegisterArgs { title, description, file_hash, license_type, metadata, expiry, expiry_format },
+    )
+}
+
+#[update]
+fn register_ip_batch(entries: Vec<RegisterArgs>) -> Vec<Result<(), String>> {
+    let caller = ic_cdk::api::caller();
+    entries
+        .into_iter()
+        .map(|args| register_ip_internal(caller, args))
+        .collect()
+}
+
 #[query]
-fn get_ip_registration(owner: Principal) -> Option<IPRegistration> {
-    REGISTRY.with(|registry| registry.borrow().get(&PrincipalWrapper(owner)))
+fn get_ip_registration(file_hash: String) -> Option<IPRegistration> {
+    REGISTRY.with(|registry| registry.borrow().get(&HashKey(file_hash)))
+}
+
+#[query]
+fn batch_get(file_hashes: Vec<String>) -> Vec<Option<IPRegistration>> {
+    REGISTRY.with(|registry| {
+        let registry = registry.borrow();
+        file_hashes
+            .into_iter()
+            .map(|file_hash| registry.get(&HashKey(file_hash)))
+            .collect()
+    })
+}
+
+#[query]
+fn get_ip_registrations_for_owner(owner: Principal) -> Vec<IPRegistration> {
+    let file_hashes = OWNER_INDEX
+        .with(|index| index.borrow().get(&PrincipalWrapper(owner)))
+        .unwrap_or_default()
+        .0;
+
+    REGISTRY.with(|registry| {
+        let registry = registry.borrow();
+        file_hashes
+            .into_iter()
+            .filter_map(|hash| registry.get(&HashKey(hash)))
+            .collect()
+    })
+}
+
+#[query]
+fn get_changes_since(seq: u64) -> (u64, Vec<IPRegistration>) {
+    let high_water_mark = SEQ_COUNTER.with(|counter| counter.borrow().get().0);
+    let changes = REGISTRY.with(|registry| {
+        registry
+            .borrow()
+            .iter()
+            .filter(|(_, reg)| reg.last_modified_seq > seq)
+            .map(|(_, reg)| reg)
+            .collect()
+    });
+    (high_water_mark, changes)
 }
 
 #[query]
@@ -208,17 +876,332 @@ fn verify_ownership(owner: Principal, file_hash: String) -> bool {
     REGISTRY.with(|registry| {
         registry
             .borrow()
-            .get(&PrincipalWrapper(owner))
-            .map_or(false, |reg| reg.file_hash == file_hash)
+            .get(&HashKey(file_hash))
+            .map_or(false, |reg| reg.owner == PrincipalWrapper(owner))
     })
 }
 
+fn require_owner(key: &HashKey, caller: Principal) -> Result<(), String> {
+    let registration = REGISTRY
+        .with(|registry| registry.borrow().get(key))
+        .ok_or_else(|| "IP registration not found".to_string())?;
+    if registration.owner != PrincipalWrapper(caller) {
+        return Err("You don't own this IP".to_string());
+    }
+    Ok(())
+}
 
+// `seal_file` cryptographically binds `file_hash` to whatever chunks are
+// stored at the time it's called; allowing further chunk writes afterwards
+// would let an owner silently serve different bytes under that same hash.
+fn require_owner_and_unsealed(key: &HashKey, caller: Principal) -> Result<(), String> {
+    let registration = REGISTRY
+        .with(|registry| registry.borrow().get(key))
+        .ok_or_else(|| "IP registration not found".to_string())?;
+    if registration.owner != PrincipalWrapper(caller) {
+        return Err("You don't own this IP".to_string());
+    }
+    if registration.sealed {
+        return Err("file is sealed and its chunks can no longer be modified".to_string());
+    }
+    Ok(())
+}
+
+#[update]
+fn upload_chunk(file_hash: String, offset: u64, bytes: Vec<u8>) -> Result<(), String> {
+    let key = HashKey(file_hash);
+    require_owner_and_unsealed(&key, ic_cdk::api::caller())?;
+
+    if offset % CHUNK_SIZE != 0 {
+        return Err(format!("offset must be aligned to {CHUNK_SIZE} bytes"));
+    }
+    if bytes.len() as u64 > CHUNK_SIZE {
+        return Err(format!("chunk exceeds the maximum chunk size of {CHUNK_SIZE} bytes"));
+    }
+
+    let chunk_index = (offset / CHUNK_SIZE) as u32;
+    FILE_CHUNKS.with(|chunks| {
+        chunks.borrow_mut().insert(ChunkKey(key, chunk_index), ChunkData(bytes));
+    });
+
+    Ok(())
+}
+
+#[query]
+fn get_file_range(file_hash: String, offset: u64, len: u64) -> Result<Vec<u8>, String> {
+    if len == 0 {
+        return Ok(Vec::new());
+    }
+    if len > MAX_RANGE_LEN {
+        return Err(format!("requested range exceeds the maximum of {MAX_RANGE_LEN} bytes per call"));
+    }
+
+    let key = HashKey(file_hash);
+    require_owner(&key, ic_cdk::api::caller())?;
+
+    let start_chunk = offset / CHUNK_SIZE;
+    let end_chunk = (offset + len - 1) / CHUNK_SIZE;
+
+    FILE_CHUNKS.with(|chunks| {
+        let chunks = chunks.borrow();
+        let mut result = Vec::with_capacity(len as usize);
+        for chunk_index in start_chunk..=end_chunk {
+            let chunk = chunks
+                .get(&ChunkKey(key.clone(), chunk_index as u32))
+                .ok_or_else(|| format!("missing chunk {chunk_index} for requested range"))?;
+
+            let chunk_start = chunk_index * CHUNK_SIZE;
+            let lo = (offset.max(chunk_start) - chunk_start) as usize;
+            let hi = ((offset + len).min(chunk_start + CHUNK_SIZE) - chunk_start) as usize;
+            if hi > chunk.0.len() {
+                return Err(format!(
+                    "chunk {chunk_index} is only {} bytes, too short for the requested range",
+                    chunk.0.len()
+                ));
+            }
+            result.extend_from_slice(&chunk.0[lo..hi]);
+        }
+        Ok(result)
+    })
+}
+
+#[update]
+fn seal_file(file_hash: String) -> Result<(), String> {
+    let key = HashKey(file_hash.clone());
+    require_owner(&key, ic_cdk::api::caller())?;
+
+    let content = FILE_CHUNKS.with(|chunks| {
+        let chunks = chunks.borrow();
+        let mut content = Vec::new();
+        let mut chunk_index = 0u32;
+        while let Some(chunk) = chunks.get(&ChunkKey(key.clone(), chunk_index)) {
+            content.extend_from_slice(&chunk.0);
+            chunk_index += 1;
+        }
+        content
+    });
+
+    if content.is_empty() {
+        return Err("no chunks uploaded for this file".to_string());
+    }
+
+    let computed_hash = to_hex(&Sha256::digest(&content));
+    if computed_hash != file_hash {
+        return Err(format!(
+            "stored content does not match file_hash (expected {file_hash}, got {computed_hash})"
+        ));
+    }
+
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        if let Some(mut reg) = registry.get(&key) {
+            reg.sealed = true;
+            reg.last_modified_seq = next_seq();
+            registry.insert(key, reg);
+        }
+    });
+
+    Ok(())
+}
 
 // For Candid interface generation
 candid::export_service!();
 
+#[derive(CandidType, Deserialize, Serialize, Clone)]
+struct InitArg {
+    admin: Principal,
+}
+
 #[ic_cdk::init]
-fn init() {
-    // Optional initialization logic
-}
\ No newline at end of file
+fn init(arg: InitArg) {
+    ADMIN.with(|admin| {
+        admin
+            .borrow_mut()
+            .set(PrincipalWrapper(arg.admin))
+            .expect("failed to set admin");
+    });
+    // A fresh install always starts on the current schema; there's no legacy
+    // data to migrate, so this isn't something the caller should choose.
+    SCHEMA_VERSION.with(|version| {
+        version
+            .borrow_mut()
+            .set(SchemaVersion(CURRENT_SCHEMA_VERSION))
+            .expect("failed to set schema version");
+    });
+    start_expiry_timer();
+}
+
+#[ic_cdk::pre_upgrade]
+fn pre_upgrade() {
+    // Everything the canister holds already lives in stable structures
+    // managed by MEMORY_MANAGER, so there is nothing to serialize here.
+}
+
+#[ic_cdk::post_upgrade]
+fn post_upgrade() {
+    let persisted_version = SCHEMA_VERSION.with(|version| version.borrow().get().0);
+    run_migrations(persisted_version);
+    SCHEMA_VERSION.with(|version| {
+        version
+            .borrow_mut()
+            .set(SchemaVersion(CURRENT_SCHEMA_VERSION))
+            .expect("failed to set schema version");
+    });
+    start_expiry_timer();
+}
+
+const EXPIRY_SWEEP_INTERVAL_SECS: u64 = 60;
+
+// Periodically flips any registration whose `expiry` has passed over to
+// `RegistrationStatus::Expired`, so expiry is enforced without a client
+// having to call `update_registration_status` itself.
+fn start_expiry_timer() {
+    set_timer_interval(Duration::from_secs(EXPIRY_SWEEP_INTERVAL_SECS), || {
+        let expired_count = sweep_expired_registrations();
+        if expired_count > 0 {
+            ic_cdk::println!("expiry sweep: marked {expired_count} registration(s) as Expired");
+        }
+    });
+}
+
+fn sweep_expired_registrations() -> u64 {
+    let now = ic_cdk::api::time();
+    REGISTRY.with(|registry| {
+        let mut registry = registry.borrow_mut();
+        let expired_keys: Vec<HashKey> = registry
+            .iter()
+            .filter(|(_, reg)| reg.status != RegistrationStatus::Expired && reg.expiry < now)
+            .map(|(key, _)| key)
+            .collect();
+
+        for key in &expired_keys {
+            if let Some(mut reg) = registry.get(key) {
+                reg.status = RegistrationStatus::Expired;
+                reg.last_modified_seq = next_seq();
+                registry.insert(key.clone(), reg);
+            }
+        }
+        expired_keys.len() as u64
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn sample_v1() -> IPRegistrationV1 {
+        IPRegistrationV1 {
+            owner: PrincipalWrapper(Principal::anonymous()),
+            title: "title".to_string(),
+            description: "description".to_string(),
+            timestamp: 1,
+            file_hash: "abc123".to_string(),
+            license_type: "MIT".to_string(),
+            metadata: HashMap::new(),
+            transfer_history: Vec::new(),
+            status: RegistrationStatus::Active,
+        }
+    }
+
+    fn sample_v2() -> IPRegistrationV2 {
+        IPRegistrationV2 {
+            owner: PrincipalWrapper(Principal::anonymous()),
+            title: "title".to_string(),
+            description: "description".to_string(),
+            timestamp: 1,
+            file_hash: "abc123".to_string(),
+            license_type: "MIT".to_string(),
+            metadata: HashMap::new(),
+            transfer_history: Vec::new(),
+            status: RegistrationStatus::Active,
+            expiry: 42,
+        }
+    }
+
+    fn sample_v3() -> IPRegistrationV3 {
+        IPRegistrationV3 {
+            owner: PrincipalWrapper(Principal::anonymous()),
+            title: "title".to_string(),
+            description: "description".to_string(),
+            timestamp: 1,
+            file_hash: "abc123".to_string(),
+            license_type: "MIT".to_string(),
+            metadata: HashMap::new(),
+            transfer_history: Vec::new(),
+            status: RegistrationStatus::Active,
+            expiry: 42,
+            last_modified_seq: 7,
+        }
+    }
+
+    #[test]
+    fn upgrade_v1_defaults_expiry_and_seq_and_preserves_other_fields() {
+        let old = sample_v1();
+        let migrated = upgrade_v1(sample_v1());
+        assert_eq!(migrated.file_hash, old.file_hash);
+        assert_eq!(migrated.title, old.title);
+        assert_eq!(migrated.expiry, u64::MAX);
+        assert_eq!(migrated.last_modified_seq, 0);
+        assert!(!migrated.sealed);
+    }
+
+    #[test]
+    fn upgrade_v2_preserves_expiry_but_resets_seq() {
+        let old = sample_v2();
+        let migrated = upgrade_v2(sample_v2());
+        assert_eq!(migrated.expiry, old.expiry);
+        assert_eq!(migrated.last_modified_seq, 0);
+        assert!(!migrated.sealed);
+    }
+
+    #[test]
+    fn upgrade_v3_preserves_expiry_and_seq_but_defaults_sealed() {
+        let old = sample_v3();
+        let migrated = upgrade_v3(sample_v3());
+        assert_eq!(migrated.expiry, old.expiry);
+        assert_eq!(migrated.last_modified_seq, old.last_modified_seq);
+        assert!(!migrated.sealed);
+    }
+
+    #[test]
+    fn parse_expiry_unix_timestamp() {
+        let nanos = parse_expiry("unix:1700000000", &Conversion::Timestamp).unwrap();
+        assert_eq!(nanos, 1_700_000_000_000_000_000);
+    }
+
+    #[test]
+    fn parse_expiry_rfc3339() {
+        let nanos = parse_expiry("2023-11-14T22:13:20Z", &Conversion::Timestamp).unwrap();
+        assert_eq!(nanos, 1_700_000_000_000_000_000);
+    }
+
+    #[test]
+    fn parse_expiry_timestamp_fmt_treats_input_as_utc() {
+        let nanos =
+            parse_expiry("2023-11-14 22:13:20", &Conversion::TimestampFmt("%Y-%m-%d %H:%M:%S".to_string()))
+                .unwrap();
+        assert_eq!(nanos, 1_700_000_000_000_000_000);
+    }
+
+    #[test]
+    fn parse_expiry_timestamp_tz_fmt_honors_offset() {
+        let nanos = parse_expiry(
+            "2023-11-15 00:13:20 +02:00",
+            &Conversion::TimestampTZFmt("%Y-%m-%d %H:%M:%S %:z".to_string()),
+        )
+        .unwrap();
+        assert_eq!(nanos, 1_700_000_000_000_000_000);
+    }
+
+    #[test]
+    fn parse_expiry_rejects_before_epoch() {
+        let result = parse_expiry("unix:-1", &Conversion::Timestamp);
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn parse_expiry_rejects_malformed_input() {
+        let result = parse_expiry("not a date", &Conversion::Timestamp);
+        assert!(result.is_err());
+    }
+}